@@ -0,0 +1,295 @@
+//! A Chase-Lev work-stealing deque: one "worker" owns the bottom of a
+//! growable circular buffer and pushes/pops there without contention;
+//! other threads hold cloneable "stealer" handles onto the top.
+
+use crate::epoch::Collector;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicIsize, AtomicPtr, Ordering};
+use std::sync::Arc;
+
+/// A growable circular buffer of slots. Never shrinks; a stealer may still
+/// be reading a buffer the owner has already grown past, so retired
+/// buffers are freed through `Inner`'s [`Collector`] rather than directly.
+struct Buffer<T> {
+    slots: Box<[MaybeUninit<T>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> Self {
+        let mut slots = Vec::with_capacity(capacity);
+        slots.resize_with(capacity, MaybeUninit::uninit);
+        Self { slots: slots.into_boxed_slice() }
+    }
+
+    fn capacity(&self) -> isize {
+        self.slots.len() as isize
+    }
+
+    unsafe fn write(&self, index: isize, value: T) {
+        let slot = self.slots[index as usize % self.slots.len()].as_ptr() as *mut T;
+        slot.write(value);
+    }
+
+    unsafe fn read(&self, index: isize) -> T {
+        let slot = self.slots[index as usize % self.slots.len()].as_ptr();
+        slot.read()
+    }
+}
+
+struct Inner<T> {
+    top: AtomicIsize,
+    bottom: AtomicIsize,
+    buffer: AtomicPtr<Buffer<T>>,
+
+    /// Reclaims buffers retired by `grow` once no stealer can still be
+    /// reading them.
+    collector: Collector,
+}
+
+/// The single owning handle of a [`WorkStealingDeque`]. `push`/`pop` only
+/// ever contend with `steal`, never with another `Worker`.
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// A cloneable handle that may only `steal` from the bottom end's owner.
+#[derive(Clone)]
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The outcome of [`Stealer::steal`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    /// The deque was empty.
+    Empty,
+    /// Lost a race with another steal or a concurrent `pop`; try again.
+    Retry,
+    /// Stole a value.
+    Success(T),
+}
+
+const INITIAL_CAPACITY: usize = 32;
+
+/// Construct a work-stealing deque, returning its owning [`Worker`] handle.
+/// Clone [`Worker::stealer`] to hand out more stealers.
+pub fn worker<T>() -> Worker<T> {
+    let inner = Arc::new(Inner {
+        top: AtomicIsize::new(0),
+        bottom: AtomicIsize::new(0),
+        buffer: AtomicPtr::new(Box::into_raw(Box::new(Buffer::new(INITIAL_CAPACITY)))),
+        collector: Collector::new(),
+    });
+
+    Worker { inner }
+}
+
+impl<T> Worker<T> {
+    /// Obtain another handle that can steal from this deque's top.
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer { inner: self.inner.clone() }
+    }
+
+    /// Push `value` onto the bottom of the deque, growing the backing
+    /// buffer first if it's full.
+    pub fn push(&self, value: T) {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Acquire);
+
+        let mut buffer = self.inner.buffer.load(Ordering::Relaxed);
+
+        // SAFETY: only the worker ever grows the buffer, so no other
+        // thread is concurrently mutating `buffer`.
+        if bottom - top >= unsafe { (*buffer).capacity() } {
+            buffer = self.grow(buffer, bottom, top);
+        }
+
+        // SAFETY: slot `bottom` is below any index a concurrent `steal`
+        // could be reading (steals only touch `top..bottom`), so writing
+        // it is exclusive to us.
+        unsafe { (*buffer).write(bottom, value) };
+
+        // Publish the new slot before bumping `bottom`, so a stealer that
+        // observes the new `bottom` also observes the write above.
+        self.inner.bottom.store(bottom + 1, Ordering::Release);
+    }
+
+    /// Double the backing buffer, copying over the live `top..bottom`
+    /// range, and publish it as current.
+    fn grow(&self, old: *mut Buffer<T>, bottom: isize, top: isize) -> *mut Buffer<T> {
+        // SAFETY: only the worker thread ever calls `grow`.
+        let capacity = unsafe { (*old).capacity() } as usize * 2;
+        let new = Box::into_raw(Box::new(Buffer::new(capacity)));
+
+        for i in top..bottom {
+            // SAFETY: `i` is within the live range of `old`, and hasn't
+            // been read out by a steal yet (that would have moved `top`
+            // past it).
+            unsafe { (*new).write(i, (*old).read(i)) };
+        }
+
+        self.inner.buffer.store(new, Ordering::Release);
+
+        // A stealer may have loaded `old` before the store above; defer
+        // freeing it until no such stealer could still be pinned against
+        // it, instead of leaking it (the old behavior) or freeing it
+        // outright (a use-after-free for a racing `steal`).
+        let addr = old as usize;
+        let guard = self.inner.collector.pin();
+        guard.defer_free(move || unsafe { drop(Box::from_raw(addr as *mut Buffer<T>)) });
+
+        new
+    }
+
+    /// Pop a value from the bottom of the deque, or `None` if it's empty.
+    pub fn pop(&self) -> Option<T> {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = self.inner.buffer.load(Ordering::Relaxed);
+        self.inner.bottom.store(bottom, Ordering::Relaxed);
+
+        let top = self.inner.top.load(Ordering::Acquire);
+
+        if top > bottom {
+            // Already empty; restore `bottom` and bail.
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+
+        // SAFETY: `bottom` is still within the buffer we just loaded, and
+        // nothing else writes to it before the race below is resolved.
+        let value = unsafe { (*buffer).read(bottom) };
+
+        if top == bottom {
+            // This was the last element; resolve the race against a
+            // concurrent steal with a CAS on `top`.
+            let won = self
+                .inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+
+            if !won {
+                // A stealer got it first. We still speculatively read
+                // `value` above, so forget our copy rather than dropping
+                // it: the stealer's own read is the one real owner now.
+                std::mem::forget(value);
+                return None;
+            }
+        }
+
+        Some(value)
+    }
+}
+
+impl<T> Stealer<T> {
+    /// Try to steal a value from the top of the deque.
+    pub fn steal(&self) -> Steal<T> {
+        // Pin for the duration of the read below, so a concurrent `grow`
+        // defers freeing the buffer we're about to read instead of racing
+        // us to free it.
+        let _guard = self.inner.collector.pin();
+
+        let top = self.inner.top.load(Ordering::Acquire);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+
+        if top >= bottom {
+            return Steal::Empty;
+        }
+
+        let buffer = self.inner.buffer.load(Ordering::Acquire);
+
+        // SAFETY: `top` is within the `top..bottom` range that `buffer`
+        // was just observed to hold; the worker never writes to slot
+        // `top` while it's still reachable from a stealer's perspective.
+        let value = unsafe { (*buffer).read(top) };
+
+        match self
+            .inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+        {
+            Ok(_) => Steal::Success(value),
+            Err(_) => {
+                // Lost the race to another steal or to `pop`; `value` was
+                // never truly ours, so don't drop it.
+                std::mem::forget(value);
+                Steal::Retry
+            }
+        }
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = self.top.load(Ordering::Relaxed);
+        let bottom = self.bottom.load(Ordering::Relaxed);
+        let buffer = self.buffer.load(Ordering::Relaxed);
+
+        // SAFETY: `Inner` is being dropped, so nothing else can still be
+        // reading `buffer`.
+        unsafe {
+            for i in top..bottom {
+                drop((*buffer).read(i));
+            }
+
+            drop(Box::from_raw(buffer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{worker, Steal};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+
+    #[test]
+    fn every_item_popped_or_stolen_exactly_once() {
+        const NUM_ITEMS: usize = 10_000;
+        const NUM_STEALERS: usize = 8;
+
+        let owner = worker::<usize>();
+        let seen = Mutex::new(HashSet::new());
+        let remaining = AtomicUsize::new(NUM_ITEMS);
+
+        thread::scope(|scope| {
+            for i in 0..NUM_ITEMS {
+                owner.push(i);
+            }
+
+            for _ in 0..NUM_STEALERS {
+                let stealer = owner.stealer();
+                let seen = &seen;
+                let remaining = &remaining;
+
+                scope.spawn(move || loop {
+                    if remaining.load(Ordering::Relaxed) == 0 {
+                        return;
+                    }
+
+                    match stealer.steal() {
+                        Steal::Success(item) => {
+                            assert!(seen.lock().unwrap().insert(item), "duplicate steal");
+                            remaining.fetch_sub(1, Ordering::Relaxed);
+                        }
+                        Steal::Empty => return,
+                        Steal::Retry => continue,
+                    }
+                });
+            }
+
+            while let Some(item) = owner.pop() {
+                assert!(seen.lock().unwrap().insert(item), "duplicate pop");
+                remaining.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+
+        let seen = seen.into_inner().unwrap();
+        assert_eq!(seen.len(), NUM_ITEMS);
+        assert_eq!(seen, (0..NUM_ITEMS).collect());
+    }
+}
@@ -0,0 +1,221 @@
+//! Epoch-based memory reclamation, shared by the crate's lock-free data
+//! structures.
+//!
+//! A thread that wants to read or unlink data behind a shared pointer pins
+//! itself with [`Collector::pin`], which hands back a [`Guard`]. While
+//! pinned, the thread publishes the collector's current global epoch into
+//! its own registry slot, and the collector will not reclaim anything
+//! retired at or after that epoch until the thread unpins (or re-pins at a
+//! later one). Rather than freeing a retired node immediately, callers hand
+//! it to [`Guard::defer_free`], which files it into the bag for the current
+//! epoch. Each thread accumulates its own bags through a
+//! [`ThreadLocal`], so filing a defer never contends with another
+//! thread's. Periodically the collector tries to advance the global
+//! epoch, which only succeeds once every pinned thread has caught up, and
+//! sweeps every thread's bag for the one that is now two epochs stale.
+
+use crate::thread_local::ThreadLocal;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The collector cycles through three epochs: the current one, the
+/// previous one (which pinned threads may still observe), and the one
+/// before that (which is safe to free).
+const EPOCH_COUNT: usize = 3;
+
+/// A closure that frees some previously-retired memory.
+type Deferred = Box<dyn FnOnce() + Send>;
+
+/// A thread's slot in a [`Collector`]'s registry.
+struct Local {
+    /// The epoch this thread last pinned at. Only meaningful while
+    /// `pinned` is `true`.
+    epoch: AtomicUsize,
+
+    /// Whether this thread currently holds a live `Guard`.
+    pinned: AtomicBool,
+}
+
+thread_local! {
+    /// Every thread may participate in several collectors at once (e.g. a
+    /// stack and a queue each own one), so we key each thread's locals by
+    /// the collector's id.
+    static LOCALS: RefCell<HashMap<u64, Arc<Local>>> = RefCell::new(HashMap::new());
+}
+
+/// Hands out a unique id to every [`Collector`], so a short-lived
+/// collector can't be confused with a different, later one that the
+/// allocator happens to place at the same address.
+static NEXT_COLLECTOR_ID: AtomicU64 = AtomicU64::new(0);
+
+/// An epoch-based garbage collector.
+///
+/// Data structures that need to free memory while other threads might
+/// still hold a reference to it own a `Collector` and route retired nodes
+/// through it instead of freeing them directly.
+pub struct Collector {
+    /// Uniquely identifies this collector among all that have ever
+    /// existed, so [`LOCALS`] can't alias a dead collector's stale entry
+    /// onto a new one allocated at the same address.
+    id: u64,
+    epoch: AtomicUsize,
+    locals: Mutex<Vec<Arc<Local>>>,
+
+    /// Each thread's own per-epoch bags, so that filing a defer only ever
+    /// touches the filing thread's own storage.
+    bags: ThreadLocal<Mutex<[Vec<Deferred>; EPOCH_COUNT]>>,
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_COLLECTOR_ID.fetch_add(1, Ordering::Relaxed),
+            epoch: AtomicUsize::new(0),
+            locals: Mutex::new(Vec::new()),
+            bags: ThreadLocal::new(),
+        }
+    }
+
+    /// Get (or lazily create and register) the calling thread's slot in
+    /// this collector.
+    fn local(&self) -> Arc<Local> {
+        let key = self.id;
+
+        LOCALS.with(|locals| {
+            locals
+                .borrow_mut()
+                .entry(key)
+                .or_insert_with(|| {
+                    let local = Arc::new(Local {
+                        epoch: AtomicUsize::new(self.epoch.load(Ordering::Relaxed)),
+                        pinned: AtomicBool::new(false),
+                    });
+
+                    self.locals.lock().unwrap().push(local.clone());
+                    local
+                })
+                .clone()
+        })
+    }
+
+    /// Pin the current thread. While the returned guard is alive, the
+    /// collector will not reclaim anything retired at or after the epoch
+    /// observed here.
+    pub fn pin(&self) -> Guard<'_> {
+        let local = self.local();
+
+        // Publish the current global epoch before touching any shared
+        // pointers. `SeqCst` ensures this becomes visible to any thread
+        // trying to advance the epoch before we read anything guarded by
+        // the pin.
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        local.epoch.store(epoch, Ordering::SeqCst);
+        local.pinned.store(true, Ordering::SeqCst);
+
+        Guard { collector: self, local }
+    }
+
+    /// File `run` into the calling thread's bag for `epoch` and try to
+    /// advance the global epoch, freeing older garbage if every pinned
+    /// thread has caught up.
+    fn defer_free(&self, epoch: usize, run: Deferred) {
+        let bag = self.bags.get_or(|| Mutex::new([Vec::new(), Vec::new(), Vec::new()]));
+        bag.lock().unwrap()[epoch % EPOCH_COUNT].push(run);
+        self.try_advance();
+    }
+
+    /// Try to bump the global epoch by one (mod [`EPOCH_COUNT`]). This only
+    /// succeeds if every currently-pinned thread's local epoch equals the
+    /// global one, which guarantees no thread can still hold a reference
+    /// into data retired two epochs ago; that bag is then freed.
+    fn try_advance(&self) {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+
+        let all_caught_up = self
+            .locals
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|local| !local.pinned.load(Ordering::SeqCst) || local.epoch.load(Ordering::SeqCst) == epoch);
+
+        if !all_caught_up {
+            return;
+        }
+
+        let next = (epoch + 1) % EPOCH_COUNT;
+
+        if self
+            .epoch
+            .compare_exchange(epoch, next, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            // Another thread already advanced the epoch.
+            return;
+        }
+
+        // The bag that is now two epochs behind `next` can no longer be
+        // observed by any thread: pinning publishes the *current* epoch,
+        // so a thread would have needed to be pinned at `next + 1`, which
+        // doesn't exist yet. Sweep every thread's bag for it.
+        let stale = (next + 1) % EPOCH_COUNT;
+
+        for bag in self.bags.iter() {
+            let garbage = std::mem::take(&mut bag.lock().unwrap()[stale]);
+
+            for free in garbage {
+                free();
+            }
+        }
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        // The collector itself is being torn down, so no thread can still
+        // be pinned against it; every bag's closures are safe to run right
+        // now regardless of which epoch they were filed under, rather than
+        // waiting for `try_advance` to sweep them (which would never
+        // happen again once this runs).
+        for bag in self.bags.iter() {
+            for epoch in bag.lock().unwrap().iter_mut() {
+                for free in std::mem::take(epoch) {
+                    free();
+                }
+            }
+        }
+    }
+}
+
+/// Proof that the current thread is pinned against a [`Collector`].
+///
+/// Dropping the guard unpins the thread.
+pub struct Guard<'a> {
+    collector: &'a Collector,
+    local: Arc<Local>,
+}
+
+impl Guard<'_> {
+    /// Defer running `f` until no thread pinned against this collector
+    /// could still be observing whatever it frees.
+    pub fn defer_free<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let epoch = self.collector.epoch.load(Ordering::SeqCst);
+        self.collector.defer_free(epoch, Box::new(f));
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.local.pinned.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod deque;
+pub mod epoch;
+pub mod pool;
+pub mod queue;
+pub mod spsc;
+pub mod stack;
+pub mod thread_local;
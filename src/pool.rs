@@ -0,0 +1,158 @@
+//! A thread-pool executor built on top of [`crate::queue::Queue`], so
+//! users don't have to pull in crossbeam or rayon just to run closures on
+//! a fixed set of worker threads.
+
+use crate::queue::Queue;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How long a parked worker waits before re-checking the queue, in case it
+/// raced a `spawn`'s wake-up.
+const PARK_TIMEOUT: Duration = Duration::from_millis(10);
+
+enum Task {
+    Run(Job),
+    Stop,
+}
+
+struct Shared {
+    queue: Queue<Task>,
+    lock: Mutex<()>,
+    notify: Condvar,
+
+    /// Panics caught from job closures, to be re-raised on the joining
+    /// thread instead of silently swallowed.
+    panics: Mutex<Vec<Box<dyn Any + Send + 'static>>>,
+}
+
+/// A fixed-size pool of worker threads that execute boxed closures pulled
+/// off a shared lock-free queue.
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    workers: Option<Vec<JoinHandle<()>>>,
+}
+
+impl ThreadPool {
+    /// Spawn `n` worker threads, each looping on the task queue.
+    pub fn new(n: usize) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Queue::new(),
+            lock: Mutex::new(()),
+            notify: Condvar::new(),
+            panics: Mutex::new(Vec::new()),
+        });
+
+        let workers = (0..n)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || Self::work(shared))
+            })
+            .collect();
+
+        Self { shared, workers: Some(workers) }
+    }
+
+    /// Enqueue `f` to run on whichever worker picks it up next.
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.queue.enqueue(Task::Run(Box::new(f)));
+
+        // Notify under the lock. A worker that found the queue empty just
+        // before this enqueue but hasn't reached `wait` yet can still miss
+        // this wake-up; its `PARK_TIMEOUT` bounds how long it sleeps then.
+        let _guard = self.shared.lock.lock().unwrap();
+        self.shared.notify.notify_one();
+    }
+
+    fn work(shared: Arc<Shared>) {
+        loop {
+            match shared.queue.dequeue() {
+                Some(Task::Run(job)) => {
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                        shared.panics.lock().unwrap().push(payload);
+                    }
+                }
+                Some(Task::Stop) => break,
+                None => {
+                    // Nothing to do; park instead of busy-spinning. The
+                    // timeout bounds how stale this can get if we missed
+                    // a wake-up.
+                    let guard = shared.lock.lock().unwrap();
+                    let _ = shared.notify.wait_timeout(guard, PARK_TIMEOUT);
+                }
+            }
+        }
+    }
+
+    /// Enqueue one stop signal per worker and join every thread, without
+    /// re-raising any panics they caught.
+    fn shutdown(&mut self) {
+        let Some(workers) = self.workers.take() else { return };
+
+        for _ in 0..workers.len() {
+            self.shared.queue.enqueue(Task::Stop);
+        }
+
+        let _guard = self.shared.lock.lock().unwrap();
+        self.shared.notify.notify_all();
+        drop(_guard);
+
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+
+    /// Shut the pool down and wait for every worker to exit. If any
+    /// spawned job panicked, re-raises the first such panic on the
+    /// calling thread via [`panic::resume_unwind`] (so panics aren't
+    /// silently swallowed). If more than one job panicked, only the
+    /// first is re-raised; the rest are dropped.
+    pub fn join(mut self) {
+        self.shutdown();
+
+        let mut panics = self.shared.panics.lock().unwrap();
+
+        if !panics.is_empty() {
+            panic::resume_unwind(panics.remove(0));
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ThreadPool;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn runs_every_spawned_job() {
+        const NUM_JOBS: usize = 10_000;
+
+        let pool = ThreadPool::new(8);
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..NUM_JOBS {
+            let counter = counter.clone();
+            pool.spawn(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        pool.join();
+
+        assert_eq!(counter.load(Ordering::Relaxed), NUM_JOBS);
+    }
+}
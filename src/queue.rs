@@ -0,0 +1,257 @@
+use crate::epoch::Collector;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// Padding to keep `head` and `tail` on separate cache lines, since they
+/// are written by different ends of the queue and would otherwise false
+/// share.
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+struct Node<T> {
+    /// Uninitialized for the initial sentinel node. Every other node is
+    /// filled in by `enqueue` when it's allocated, and its value is moved
+    /// out by `dequeue` when the node becomes the new sentinel.
+    value: MaybeUninit<T>,
+    next: AtomicPtr<Self>,
+}
+
+impl<T> Node<T> {
+    fn sentinel() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            value: MaybeUninit::uninit(),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+/// A Michael-Scott lock-free FIFO queue.
+pub struct Queue<T> {
+    head: CachePadded<AtomicPtr<Node<T>>>,
+    tail: CachePadded<AtomicPtr<Node<T>>>,
+
+    /// Reclaims dequeued sentinel nodes once no pinned thread can still be
+    /// observing them.
+    collector: Collector,
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        let sentinel = Node::sentinel();
+
+        Self {
+            head: CachePadded(AtomicPtr::new(sentinel)),
+            tail: CachePadded(AtomicPtr::new(sentinel)),
+            collector: Collector::new(),
+        }
+    }
+
+    /// Enqueue `value` at the tail of the queue.
+    pub fn enqueue(&self, value: T) {
+        let node = Box::into_raw(Box::new(Node {
+            value: MaybeUninit::new(value),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+
+        let guard = self.collector.pin();
+
+        loop {
+            let tail = self.tail.0.load(Ordering::Acquire);
+
+            // SAFETY: `tail` is never freed while a thread can still be
+            // pinned with it loaded, since dequeue only retires the old
+            // sentinel through the epoch collector.
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+
+            // Make sure we're still looking at the real tail before
+            // trusting `next`.
+            if tail != self.tail.0.load(Ordering::Acquire) {
+                continue;
+            }
+
+            if next.is_null() {
+                // `tail` really is the last node; try to link ours after it.
+                let result = unsafe {
+                    (*tail).next.compare_exchange(
+                        ptr::null_mut(),
+                        node,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    )
+                };
+
+                if result.is_ok() {
+                    // Swing `tail` forward; if we lose this race some other
+                    // thread will do it for us (the helping protocol
+                    // below), so ignore failure.
+                    let _ = self.tail.0.compare_exchange(
+                        tail,
+                        node,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+
+                    break;
+                }
+            } else {
+                // `tail` is lagging behind the real end of the list;
+                // help it catch up before retrying.
+                let _ = self.tail.0.compare_exchange(
+                    tail,
+                    next,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+
+        drop(guard);
+    }
+
+    /// Dequeue a value from the head of the queue, or `None` if it's empty.
+    pub fn dequeue(&self) -> Option<T> {
+        let guard = self.collector.pin();
+
+        loop {
+            let head = self.head.0.load(Ordering::Acquire);
+            let tail = self.tail.0.load(Ordering::Acquire);
+
+            // SAFETY: `head` is kept alive by our pin until we retire it
+            // below, and `next` only ever points at nodes allocated by
+            // `enqueue`, which are never freed while reachable.
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+
+            if head != self.head.0.load(Ordering::Acquire) {
+                continue;
+            }
+
+            if head == tail {
+                if next.is_null() {
+                    // The queue is empty.
+                    return None;
+                }
+
+                // `tail` is lagging; help it catch up and retry.
+                let _ = self.tail.0.compare_exchange(
+                    tail,
+                    next,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+
+                continue;
+            }
+
+            // Read the value out of `next` before the CAS below logically
+            // removes it by making it the new sentinel.
+            //
+            // SAFETY: `next` is non-null and reachable, so its `value` has
+            // been initialized by `enqueue` and not yet read.
+            let value = unsafe { ptr::read((*next).value.as_ptr()) };
+
+            if self
+                .head
+                .0
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // `head` is now unreachable (the old sentinel); defer
+                // freeing it until no pinned thread can still observe it.
+                let addr = head as usize;
+                guard.defer_free(move || unsafe {
+                    drop(Box::from_raw(addr as *mut Node<T>));
+                });
+
+                return Some(value);
+            }
+
+            // Lost the race; someone else dequeued first. We already read
+            // `value` out, but didn't win the CAS, so put it back by
+            // forgetting our read and retrying with the fresh state. Since
+            // another thread's CAS would only succeed with the same
+            // `next`, losing the race here means another thread raced us
+            // onto the same node, which the CAS above protects against; so
+            // this branch means `head` moved and we must retry from
+            // scratch without having removed anything.
+            std::mem::forget(value);
+        }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+
+        // SAFETY: `drop` has exclusive access, so the remaining sentinel
+        // is not observed by anyone else.
+        unsafe { drop(Box::from_raw(self.head.0.load(Ordering::Relaxed))) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Queue;
+    use std::num::Wrapping;
+    use std::thread;
+
+    #[test]
+    fn enqueue_then_dequeue() {
+        const NUM_THREADS: usize = 10;
+        const NUM_ENQUEUE_PER_THREAD: usize = 10;
+
+        let queue = Queue::<usize>::new();
+
+        thread::scope(|scope| {
+            let mut handles = Vec::new();
+
+            // Spawn NUM_THREADS, each of them locally adding
+            // NUM_ENQUEUE_PER_THREAD random numbers, and enqueuing them.
+            for _ in 0..NUM_THREADS {
+                handles.push(scope.spawn(|| {
+                    let mut sum = Wrapping(0);
+
+                    for _ in 0..NUM_ENQUEUE_PER_THREAD {
+                        let random = rand::random::<usize>();
+                        sum += random;
+                        queue.enqueue(random);
+                    }
+
+                    sum
+                }));
+            }
+
+            let enqueue_sum = handles
+                .drain(..)
+                .map(|handle| handle.join().expect("no panics"))
+                .fold(Wrapping(0), |a, b| a + b);
+
+            // Create NUM_THREADS, each of them dequeuing until empty and
+            // adding locally.
+            for _ in 0..NUM_THREADS {
+                handles.push(scope.spawn(|| {
+                    let mut sum = Wrapping(0);
+
+                    while let Some(number) = queue.dequeue() {
+                        sum += number;
+                    }
+
+                    sum
+                }));
+            }
+
+            let dequeue_sum = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("no panics"))
+                .fold(Wrapping(0), |a, b| a + b);
+
+            assert_eq!(enqueue_sum, dequeue_sum);
+        })
+    }
+}
@@ -0,0 +1,245 @@
+//! A single-producer/single-consumer FIFO queue, after Dmitry Vyukov's
+//! intrusive SPSC queue design.
+//!
+//! Unlike [`crate::stack::Stack`] or [`crate::queue::Queue`], this makes no
+//! attempt to support more than one producer or consumer. The producer
+//! owns `tail`, `first` and `tail_copy`; the consumer owns its own
+//! traversal cursor. The only state genuinely shared between the two ends
+//! is each node's `next` pointer (the link the consumer follows to find
+//! data the producer just published) and `head`, which the consumer uses
+//! to tell the producer how far it's safe to recycle old nodes. Because
+//! there is never more than one writer for either, both are plain
+//! `Release` stores and `Acquire` loads rather than CAS loops.
+//!
+//! # Safety
+//!
+//! [`spsc`] hands out exactly one [`Producer`] and one [`Consumer`].
+//! Neither is `Clone`, so the single-producer/single-consumer invariant
+//! that the rest of this module relies on is enforced by construction:
+//! only ever push from the `Producer` and only ever pop from the
+//! `Consumer`.
+
+use std::cell::Cell;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+struct Node<T> {
+    value: Option<T>,
+    next: AtomicPtr<Self>,
+}
+
+impl<T> Node<T> {
+    fn new(value: Option<T>) -> *mut Self {
+        Box::into_raw(Box::new(Self { value, next: AtomicPtr::new(ptr::null_mut()) }))
+    }
+}
+
+struct Shared<T> {
+    /// The consumer's traversal cursor, published after every successful
+    /// pop so the producer knows which nodes are safe to recycle. Never
+    /// read back by the consumer itself.
+    head: AtomicPtr<Node<T>>,
+
+    /// Producer-owned: the most recently appended node.
+    tail: Cell<*mut Node<T>>,
+
+    /// Producer-owned: the oldest node the producer still owns, walked
+    /// forward towards `tail_copy` to recycle nodes instead of
+    /// allocating fresh ones.
+    first: Cell<*mut Node<T>>,
+
+    /// Producer's cached copy of `head`, refreshed only once `first`
+    /// catches up to it.
+    tail_copy: Cell<*mut Node<T>>,
+}
+
+/// The producer's exclusive handle onto an [`spsc`] queue.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The consumer's exclusive handle onto an [`spsc`] queue.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+
+    /// This end's own traversal cursor. Only ever touched by the
+    /// consumer, so it lives here rather than in `Shared`.
+    cursor: Cell<*mut Node<T>>,
+}
+
+// SAFETY: `Producer`/`Consumer` are only ever moved, not shared, between
+// threads (they're not `Clone`), so handing one to another thread can't
+// create concurrent access to the `Cell`s above.
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// Construct a single-producer/single-consumer queue, returning its two
+/// ends.
+pub fn spsc<T>() -> (Producer<T>, Consumer<T>) {
+    // A valueless stub so `head`/`tail`/`cursor` are never null; it gets
+    // recycled like any other node once the consumer moves past it.
+    let stub = Node::new(None);
+
+    let shared = Arc::new(Shared {
+        head: AtomicPtr::new(stub),
+        tail: Cell::new(stub),
+        first: Cell::new(stub),
+        tail_copy: Cell::new(stub),
+    });
+
+    let producer = Producer { shared: shared.clone() };
+    let consumer = Consumer { shared, cursor: Cell::new(stub) };
+
+    (producer, consumer)
+}
+
+impl<T> Producer<T> {
+    /// Push `value` onto the queue. Never blocks.
+    pub fn push(&self, value: T) {
+        let node = self.alloc(value);
+
+        // SAFETY: `tail` is only ever written by the producer, and the
+        // consumer only follows it after an Acquire load observes this
+        // Release store, by which point `node`'s fields are fully
+        // written.
+        unsafe { (*self.shared.tail.get()).next.store(node, Ordering::Release) };
+        self.shared.tail.set(node);
+    }
+
+    /// Hand back a slot for `value`: a recycled node behind the
+    /// consumer's cursor if one is available, or a fresh allocation.
+    fn alloc(&self, value: T) -> *mut Node<T> {
+        if self.shared.first.get() != self.shared.tail_copy.get() {
+            let node = self.shared.first.get();
+
+            // SAFETY: everything strictly before `tail_copy` has already
+            // been passed by the consumer (per its last published
+            // `head`), so the producer is free to reuse it. `tail_copy`
+            // itself may still be the consumer's cursor and is not reused.
+            unsafe {
+                self.shared.first.set((*node).next.load(Ordering::Relaxed));
+                (*node).value = Some(value);
+                (*node).next = AtomicPtr::new(ptr::null_mut());
+            }
+
+            return node;
+        }
+
+        // Ran out of recyclable nodes; refresh our view of how far the
+        // consumer has gotten and try again before falling back to a new
+        // allocation.
+        self.shared.tail_copy.set(self.shared.head.load(Ordering::Acquire));
+
+        if self.shared.first.get() != self.shared.tail_copy.get() {
+            return self.alloc(value);
+        }
+
+        Node::new(Some(value))
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Pop the next value, or `None` if nothing new has been pushed.
+    pub fn pop(&self) -> Option<T> {
+        let current = self.cursor.get();
+
+        // SAFETY: `current` is either the initial stub or a node we
+        // published through `head` ourselves, so its `next` link is
+        // readable; Acquire pairs with the producer's Release store in
+        // `push` to make `next`'s value fully visible once non-null.
+        let next = unsafe { (*current).next.load(Ordering::Acquire) };
+
+        if next.is_null() {
+            return None;
+        }
+
+        // SAFETY: the producer fully initializes a node before linking
+        // it in, so `next`'s value is ready to read.
+        let value = unsafe { (*next).value.take() };
+
+        self.cursor.set(next);
+
+        // Publish our new cursor so the producer knows `current` (and
+        // everything before it) is safe to recycle.
+        self.shared.head.store(next, Ordering::Release);
+
+        value
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Both ends are gone by the time `Shared` drops. `first` is the
+        // oldest node the producer still owns; everything from there
+        // through `tail` is one unbroken `.next` chain (nothing is ever
+        // unlinked in place), covering both recycled-but-not-yet-reused
+        // nodes and anything pushed but never popped. `head` is just the
+        // consumer's cursor into the middle of that same chain, so
+        // starting there would miss everything between `first` and it.
+        let mut current = *self.first.get_mut();
+        let tail = *self.tail.get_mut();
+
+        loop {
+            // SAFETY: `Shared` is being dropped, so no producer or
+            // consumer reference to these nodes is still outstanding;
+            // read `next` before freeing `current` so we don't read
+            // through a dangling pointer.
+            let next = unsafe { *(*current).next.get_mut() };
+            let done = current == tail;
+
+            unsafe { drop(Box::from_raw(current)) };
+
+            if done {
+                break;
+            }
+
+            current = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::spsc;
+    use std::num::Wrapping;
+    use std::thread;
+
+    #[test]
+    fn push_then_pop_round_trip() {
+        const NUM_PUSHES: usize = 100_000;
+
+        let (producer, consumer) = spsc::<usize>();
+
+        thread::scope(|scope| {
+            let pushed = scope.spawn(move || {
+                let mut sum = Wrapping(0);
+
+                for _ in 0..NUM_PUSHES {
+                    let random = rand::random::<usize>();
+                    sum += random;
+                    producer.push(random);
+                }
+
+                sum
+            });
+
+            let popped = scope.spawn(move || {
+                let mut sum = Wrapping(0);
+                let mut count = 0;
+
+                while count < NUM_PUSHES {
+                    if let Some(number) = consumer.pop() {
+                        sum += number;
+                        count += 1;
+                    }
+                }
+
+                sum
+            });
+
+            assert_eq!(pushed.join().expect("no panics"), popped.join().expect("no panics"));
+        })
+    }
+}
@@ -1,35 +1,149 @@
-use std::sync::atomic::{Ordering, AtomicPtr, AtomicUsize};
+use crate::epoch::Collector;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 use std::ptr;
 
 struct Node<T> {
-    value: T,
+    value: MaybeUninit<T>,
     next: *mut Self,
+
+    /// The block this node was carved out of, or null if it was heap
+    /// allocated directly with `Box::new`.
+    block: *mut Block<T>,
+}
+
+/// Marks a [`Block`] that is still accepting new nodes.
+const UNSEALED: usize = usize::MAX;
+
+/// A fixed-size batch of node storage, so that `push` can hand out slots
+/// with a `fetch_add` instead of going through the allocator on every call.
+struct Block<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<Node<T>>>]>,
+
+    /// The next free slot index, handed out by `fetch_add`. May run past
+    /// `slots.len()` while threads race to notice the block is full.
+    cursor: AtomicUsize,
+
+    /// How many of this block's handed-out slots have since been
+    /// retired. Once this equals `sealed`, the block is wholly drained.
+    drained: AtomicUsize,
+
+    /// `UNSEALED` while this is still the current block; once a new block
+    /// is linked in, set to the number of slots this one handed out, which
+    /// is always its full capacity.
+    sealed: AtomicUsize,
+}
+
+impl<T> Block<T> {
+    fn new(size: usize) -> *mut Self {
+        let mut slots = Vec::with_capacity(size);
+        slots.resize_with(size, || UnsafeCell::new(MaybeUninit::uninit()));
+
+        Box::into_raw(Box::new(Self {
+            slots: slots.into_boxed_slice(),
+            cursor: AtomicUsize::new(0),
+            drained: AtomicUsize::new(0),
+            sealed: AtomicUsize::new(UNSEALED),
+        }))
+    }
+}
+
+/// A segmented backing store for `Node`s, so that allocator traffic is
+/// amortized across `size` pushes instead of costing one `Box` per push.
+struct BlockStore<T> {
+    size: usize,
+    current: AtomicPtr<Block<T>>,
+}
+
+impl<T> BlockStore<T> {
+    fn new(size: usize) -> Self {
+        Self { size, current: AtomicPtr::new(Block::new(size)) }
+    }
+
+    /// Carve out a slot for a new node and write it in place, growing the
+    /// block list with a single CAS-free link once the current block
+    /// fills up.
+    fn alloc(&self, value: T, next: *mut Node<T>) -> *mut Node<T> {
+        loop {
+            let block = self.current.load(Ordering::Acquire);
+
+            // SAFETY: blocks are never freed while they could still be
+            // `current`, or while slots they handed out haven't all been
+            // retired yet.
+            let idx = unsafe { (*block).cursor.fetch_add(1, Ordering::Relaxed) };
+
+            if idx < self.size {
+                let slot = unsafe { (*block).slots[idx].get() };
+                unsafe { (*slot).write(Node { value: MaybeUninit::new(value), next, block }) };
+                return unsafe { (*slot).as_mut_ptr() };
+            }
+
+            // The block is full. Exactly one thread observes the exact
+            // boundary index, since `fetch_add` hands out each value once;
+            // that thread is responsible for sealing the block and
+            // linking a fresh one in. Everyone else just retries until the
+            // new block is visible.
+            if idx == self.size {
+                let fresh = Block::new(self.size);
+                self.current.store(fresh, Ordering::Release);
+                unsafe { (*block).sealed.store(self.size, Ordering::Release) };
+            }
+        }
+    }
 }
 
 pub struct Stack<T> {
     /// The pointer pointer to the top of the stack.
     top: AtomicPtr<Node<T>>,
 
-    /// The number of threads currently popping some data.
-    pops: AtomicUsize,
+    /// Reclaims nodes once no pinned thread can still be observing them.
+    collector: Collector,
 
-    /// The list of nodes to be deleted.
-    garbage: AtomicPtr<Node<T>>,
+    /// Segmented node storage, present only when constructed through
+    /// [`Stack::with_block_size`].
+    blocks: Option<BlockStore<T>>,
 }
 
 impl<T> Stack<T> {
     pub fn new() -> Self {
         Self {
             top: AtomicPtr::new(ptr::null_mut()),
-            pops: AtomicUsize::new(0),
-            garbage: AtomicPtr::new(ptr::null_mut()),
+            collector: Collector::new(),
+            blocks: None,
+        }
+    }
+
+    /// Construct a stack that carves its nodes out of fixed-size blocks of
+    /// `size` slots apiece, rather than allocating one `Box` per push.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is `0`: every zero-slot block is full from the
+    /// start, so `push` would keep sealing and replacing blocks forever
+    /// without ever finding a free slot.
+    pub fn with_block_size(size: usize) -> Self {
+        assert!(size > 0, "block size must be greater than 0");
+
+        Self {
+            top: AtomicPtr::new(ptr::null_mut()),
+            collector: Collector::new(),
+            blocks: Some(BlockStore::new(size)),
         }
     }
 
     /// Push data into stack, this allocates a new node for the given data.
     pub fn push(&self, value: T) {
         let next = self.top.load(Ordering::Relaxed);
-        let node = Box::into_raw(Box::new(Node { value, next }));
+
+        let node = match &self.blocks {
+            Some(blocks) => blocks.alloc(value, next),
+            None => Box::into_raw(Box::new(Node {
+                value: MaybeUninit::new(value),
+                next,
+                block: ptr::null_mut(),
+            })),
+        };
 
         // SAFETY: There is no other thread acccessing the node we are trying
         // to push. So it is safe to access and modify it via pointer.
@@ -43,7 +157,10 @@ impl<T> Stack<T> {
 
     /// Pop data from the stack.
     pub fn pop(&self) -> Option<T> {
-        self.pops.fetch_add(1, Ordering::SeqCst);
+        // Pin this thread for the duration of the pop. This publishes our
+        // epoch so the node we're about to unlink can't be freed out from
+        // under any thread that is still observing it.
+        let guard = self.collector.pin();
 
         // Load the top node so that we can CAS.
         let mut top = self.top.load(Ordering::Relaxed);
@@ -67,63 +184,48 @@ impl<T> Stack<T> {
             }
         }
 
-        // Read the value of the node we've just popped.
+        // Read the value out of the node we've just unlinked.
         //
-        // SAFETY: The CAS loop has succeeded, meaning, the current thread is
-        // the only one that popped the top node, and responsible for returning
-        // the value of the top node to the caller.
-        let node = unsafe { ptr::read(top) };
-
-        // Reclaim the nodes if we can.
-        unsafe { self.reclaim(top) };
-
-        Some(node.value)
+        // SAFETY: The CAS loop has succeeded, meaning the current thread is
+        // the only one that popped this node, and is responsible for
+        // returning its value to the caller. This leaves `value`
+        // uninitialized in the node, which is fine since the deferred
+        // closure below frees the node without dropping that field.
+        let value = unsafe { ptr::read((*top).value.as_ptr()) };
+
+        // Another thread may still hold a pointer to `top` that it loaded
+        // before our CAS won, so it can't be freed immediately. Defer the
+        // free until the epoch has advanced far enough that no such
+        // thread can still be pinned.
+        let addr = top as usize;
+        guard.defer_free(move || unsafe { Self::retire(addr as *mut Node<T>) });
+
+        Some(value)
     }
 
-    unsafe fn reclaim(&self, node: *mut Node<T>) {
-        let pops = self.pops.load(Ordering::SeqCst);
-
-        if pops == 1 {
-            // Capture the garbage list.
-            let garbage = self.garbage.swap(ptr::null_mut(), Ordering::SeqCst);
-
-            if self.pops.fetch_sub(1, Ordering::SeqCst) != 1 {
-                let mut node = garbage;
+    /// Free a node once it's safe to do so: directly, if it was a plain
+    /// heap allocation, or by returning its slot to its block and freeing
+    /// the whole block once every slot it ever handed out has drained.
+    unsafe fn retire(node: *mut Node<T>) {
+        let block = (*node).block;
 
-                while !node.is_null() {
-                    let next = (*node).next;
-                    let _ = Box::from_raw(node);
-                    node = next;
-                }
-            } else if !garbage.is_null() {
-                self.tie(garbage);
-            }
-
-            let _ = Box::from_raw(node);
-        } else {
-            self.tie(node);
-            self.pops.fetch_sub(1, Ordering::SeqCst);
+        if block.is_null() {
+            drop(Box::from_raw(node));
+            return;
         }
-    }
 
-    unsafe fn tie(&self, list: *mut Node<T>) {
-        let mut last = list;
+        let drained = (*block).drained.fetch_add(1, Ordering::AcqRel) + 1;
+        let sealed = (*block).sealed.load(Ordering::Acquire);
 
-        // Find the last item in the list.
-        while !(*last).next.is_null() {
-            last = (*last).next;
+        if sealed != UNSEALED && drained == sealed {
+            drop(Box::from_raw(block));
         }
+    }
+}
 
-        let mut head = self.garbage.load(Ordering::SeqCst);
-
-        loop {
-            (*last).next = head;
-
-            match self.garbage.compare_exchange_weak(head, list, Ordering::SeqCst, Ordering::SeqCst) {
-                Ok(_) => break,
-                Err(node) => head = node,
-            }
-        }
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -133,16 +235,15 @@ mod test {
     use super::Stack;
     use std::thread;
 
-    #[test]
-    fn push_then_pop() {
+    /// Pushes and then pops the same concurrent workload against whatever
+    /// stack it's given, returning `(pushed sum, popped sum)`.
+    fn push_then_pop_workload(stack: &Stack<usize>) -> (Wrapping<usize>, Wrapping<usize>) {
         const NUM_THREADS: usize = 10;
         const NUM_PUSH_PER_THREAD: usize = 10;
 
-        let stack = Stack::<usize>::new();
-
         thread::scope(|scope| {
             let mut handles = Vec::new();
-            
+
             // Spawn NUM_THREADS, each of them locally adding NUM_PUSH_PER_THREAD
             // random numbers, and pushing them to the stack.
             for _ in 0..NUM_THREADS {
@@ -189,8 +290,24 @@ mod test {
                 .map(|handle| handle.join().expect("no panics"))
                 .fold(Wrapping(0), |a, b| a + b);
 
-            // Check if they are equal, this is not allowed to panic.
-            assert_eq!(pop_sum, thread_sum);
+            (thread_sum, pop_sum)
         })
     }
+
+    #[test]
+    fn push_then_pop() {
+        let stack = Stack::<usize>::new();
+        let (thread_sum, pop_sum) = push_then_pop_workload(&stack);
+
+        // Check if they are equal, this is not allowed to panic.
+        assert_eq!(pop_sum, thread_sum);
+    }
+
+    #[test]
+    fn push_then_pop_with_blocks() {
+        let stack = Stack::<usize>::with_block_size(32);
+        let (thread_sum, pop_sum) = push_then_pop_workload(&stack);
+
+        assert_eq!(pop_sum, thread_sum);
+    }
 }
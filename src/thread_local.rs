@@ -0,0 +1,244 @@
+//! Sharded thread-local storage, modeled on the `seize` crate's retire
+//! lists: every thread gets a slot it can touch without contending on any
+//! other thread's, while still letting something else `iter()` over every
+//! thread's slot at once (e.g. a collector sweeping retired data).
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// One bucket per power-of-two range of thread ids: bucket `i` covers ids
+/// `[2^i - 1, 2^(i+1) - 2]` and holds `2^i` entries. This gives amortized
+/// `O(1)` lookup with no rehashing, and — unlike a growable `Vec` — a
+/// bucket is never moved once allocated, so a `&T` handed out from it
+/// stays valid for as long as the `ThreadLocal` lives.
+const BUCKETS: usize = usize::BITS as usize + 1;
+
+struct Entry<T> {
+    present: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Entry<T> {
+    fn empty() -> Self {
+        Self { present: AtomicBool::new(false), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+}
+
+fn bucket_capacity(bucket: usize) -> usize {
+    1 << bucket
+}
+
+/// Which bucket `id` lives in, and its index within that bucket.
+fn locate(id: usize) -> (usize, usize) {
+    let bucket = (id + 1).ilog2() as usize;
+    let index = id + 1 - bucket_capacity(bucket);
+    (bucket, index)
+}
+
+/// Sharded per-thread storage.
+pub struct ThreadLocal<T> {
+    buckets: [AtomicPtr<Entry<T>>; BUCKETS],
+}
+
+impl<T> ThreadLocal<T> {
+    pub fn new() -> Self {
+        Self { buckets: [const { AtomicPtr::new(ptr::null_mut()) }; BUCKETS] }
+    }
+
+    /// Get this thread's slot, initializing it with `f` the first time
+    /// it's touched.
+    pub fn get_or(&self, f: impl FnOnce() -> T) -> &T {
+        let (bucket, index) = locate(thread_id());
+
+        // SAFETY: `index` is within this bucket's capacity by
+        // construction of `locate`.
+        let entry = unsafe { &*self.bucket(bucket).add(index) };
+
+        if !entry.present.load(Ordering::Acquire) {
+            // SAFETY: only the thread that owns this id ever writes to
+            // its own entry, so there's no concurrent writer to race.
+            unsafe { (*entry.value.get()).write(f()) };
+            entry.present.store(true, Ordering::Release);
+        }
+
+        // SAFETY: `present` is only ever set after the value has been
+        // fully written, and entries are never overwritten afterwards.
+        unsafe { (*entry.value.get()).assume_init_ref() }
+    }
+
+    /// Lazily allocate (or return the already-allocated) bucket `bucket`.
+    fn bucket(&self, bucket: usize) -> *mut Entry<T> {
+        let slot = &self.buckets[bucket];
+        let existing = slot.load(Ordering::Acquire);
+
+        if !existing.is_null() {
+            return existing;
+        }
+
+        let capacity = bucket_capacity(bucket);
+        let mut fresh: Vec<Entry<T>> = Vec::with_capacity(capacity);
+        fresh.resize_with(capacity, Entry::empty);
+        let fresh = Box::into_raw(fresh.into_boxed_slice()) as *mut Entry<T>;
+
+        match slot.compare_exchange(ptr::null_mut(), fresh, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => fresh,
+            Err(existing) => {
+                // Lost the race to allocate this bucket; drop our
+                // redundant copy and use the winner's.
+                unsafe { drop(Box::from_raw(ptr::slice_from_raw_parts_mut(fresh, capacity))) };
+                existing
+            }
+        }
+    }
+
+    /// Iterate over every thread's stored value at once.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.buckets.iter().enumerate().flat_map(|(bucket, slot)| {
+            let ptr = slot.load(Ordering::Acquire);
+
+            // An unallocated bucket has nothing to iterate; `from_raw_parts`
+            // requires a non-null pointer even for a zero-length slice, so
+            // just hand back an empty one directly rather than building it
+            // from `ptr`.
+            let entries: &[Entry<T>] = if ptr.is_null() {
+                &[]
+            } else {
+                // SAFETY: `ptr` was allocated with exactly
+                // `bucket_capacity(bucket)` entries, and is never freed
+                // while `self` is alive.
+                unsafe { std::slice::from_raw_parts(ptr, bucket_capacity(bucket)) }
+            };
+
+            entries
+                .iter()
+                .filter(|entry| entry.present.load(Ordering::Acquire))
+                .map(|entry| {
+                    // SAFETY: see `get_or`.
+                    unsafe { (*entry.value.get()).assume_init_ref() }
+                })
+        })
+    }
+}
+
+impl<T> Default for ThreadLocal<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for ThreadLocal<T> {
+    fn drop(&mut self) {
+        for (bucket, slot) in self.buckets.iter_mut().enumerate() {
+            let ptr = *slot.get_mut();
+
+            if ptr.is_null() {
+                continue;
+            }
+
+            let capacity = bucket_capacity(bucket);
+
+            // SAFETY: we have exclusive access to `self`, and `ptr` was
+            // allocated with exactly `capacity` entries.
+            unsafe {
+                for entry in std::slice::from_raw_parts_mut(ptr, capacity) {
+                    if *entry.present.get_mut() {
+                        entry.value.get_mut().assume_init_drop();
+                    }
+                }
+
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, capacity)));
+            }
+        }
+    }
+}
+
+// SAFETY: every entry is only ever written by the thread that owns its
+// id; other threads only ever read through `iter()`, which is only sound
+// to hand out for `T: Sync` in the first place (enforced below).
+unsafe impl<T: Send> Send for ThreadLocal<T> {}
+unsafe impl<T: Send + Sync> Sync for ThreadLocal<T> {}
+
+/// Recycles thread ids through a free-list, so a bounded number of live
+/// threads uses a bounded number of slots instead of growing forever.
+struct IdManager {
+    free: Mutex<Vec<usize>>,
+    next: AtomicUsize,
+}
+
+impl IdManager {
+    const fn new() -> Self {
+        Self { free: Mutex::new(Vec::new()), next: AtomicUsize::new(0) }
+    }
+
+    fn acquire(&self) -> usize {
+        self.free.lock().unwrap().pop().unwrap_or_else(|| self.next.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn release(&self, id: usize) {
+        self.free.lock().unwrap().push(id);
+    }
+}
+
+static IDS: IdManager = IdManager::new();
+
+struct ThreadIdGuard(usize);
+
+impl Drop for ThreadIdGuard {
+    fn drop(&mut self) {
+        IDS.release(self.0);
+    }
+}
+
+thread_local! {
+    static THREAD_ID: ThreadIdGuard = ThreadIdGuard(IDS.acquire());
+}
+
+fn thread_id() -> usize {
+    THREAD_ID.with(|guard| guard.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::ThreadLocal;
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn each_thread_gets_its_own_slot() {
+        const NUM_THREADS: usize = 64;
+
+        let locals = ThreadLocal::new();
+
+        // Thread ids are recycled once a thread exits, so without this
+        // barrier a fast thread could finish (and free its id) before a
+        // later one spawns, which would then reuse its slot instead of
+        // getting a fresh one. Block every thread here until all
+        // `NUM_THREADS` are alive and have a distinct id, then assert.
+        let barrier = Barrier::new(NUM_THREADS);
+
+        thread::scope(|scope| {
+            for n in 0..NUM_THREADS {
+                let locals = &locals;
+                let barrier = &barrier;
+
+                scope.spawn(move || {
+                    // Claim this thread's id before the barrier, so every
+                    // thread has a distinct, live id by the time any of
+                    // them is allowed to proceed (and possibly exit).
+                    super::thread_id();
+                    barrier.wait();
+
+                    let value = locals.get_or(|| n);
+                    assert_eq!(*value, n);
+                    assert_eq!(*locals.get_or(|| n + 1), n);
+                });
+            }
+        });
+
+        let seen: Vec<_> = locals.iter().copied().collect();
+        assert_eq!(seen.len(), NUM_THREADS);
+    }
+}